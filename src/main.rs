@@ -1,4 +1,4 @@
-use std::{ops::RangeInclusive, time::Duration};
+use std::{collections::VecDeque, ops::RangeInclusive, time::Duration};
 
 use eframe::egui::{
     self,
@@ -20,10 +20,134 @@ fn main() {
     eframe::run_native("Viewer", options, Box::new(|cc| Box::new(App::new(cc)))).unwrap();
 }
 
+/// Multi-resolution min/max pyramid over a growing, front-trimmed sample stream.
+///
+/// `levels[k]` holds `(min, max)` buckets aggregated over `2^(k + 1)` raw samples, each
+/// built by merging a completed pair from `levels[k - 1]` (or from the raw samples for
+/// `levels[0]`). Levels are only appended to when a bucket fills, so this is O(1)
+/// amortized per incoming sample regardless of how many levels exist.
+#[derive(Default)]
+struct Pyramid {
+    levels: Vec<VecDeque<(f64, f64)>>,
+    pending: Vec<Option<(f64, f64)>>,
+    dropped: Vec<usize>,
+}
+impl Pyramid {
+    fn push(&mut self, sample: f64) {
+        self.promote((sample, sample), 0);
+    }
+    fn promote(&mut self, bucket: (f64, f64), level: usize) {
+        if level == self.levels.len() {
+            self.levels.push(VecDeque::new());
+            self.pending.push(None);
+            self.dropped.push(0);
+        }
+        match self.pending[level].take() {
+            None => self.pending[level] = Some(bucket),
+            Some((min, max)) => {
+                let merged = (min.min(bucket.0), max.max(bucket.1));
+                self.levels[level].push_back(merged);
+                self.promote(merged, level + 1);
+            }
+        }
+    }
+    /// Keeps each level's backlog in proportion to `total_dropped` raw samples having
+    /// been trimmed from the front of the retained window.
+    fn trim(&mut self, total_dropped: usize) {
+        for (level, deque) in self.levels.iter_mut().enumerate() {
+            let bucket_size = 1usize << (level + 1);
+            let want_dropped = total_dropped / bucket_size;
+            let to_drop = (want_dropped - self.dropped[level]).min(deque.len());
+            deque.drain(..to_drop);
+            self.dropped[level] += to_drop;
+        }
+    }
+}
+
+/// Running mean/min/max/RMS over the most recent `window_len` samples, updated
+/// incrementally as samples enter and leave the window: a running sum and
+/// sum-of-squares for the mean and RMS, and a pair of monotonic deques (indexed by
+/// arrival order) for the min and max, each O(1) amortized per sample.
+#[derive(Default)]
+struct WindowStats {
+    samples: VecDeque<f64>,
+    sum: f64,
+    sum_sq: f64,
+    next_index: u64,
+    min_deque: VecDeque<(u64, f64)>,
+    max_deque: VecDeque<(u64, f64)>,
+}
+impl WindowStats {
+    /// Builds a one-off `WindowStats` covering exactly `values`, for when the display
+    /// window isn't "the newest samples" (e.g. a trigger anchors it elsewhere) and the
+    /// incrementally maintained, always-newest-window stats wouldn't match.
+    fn from_slice(values: &[f64]) -> Self {
+        let mut stats = Self::default();
+        for &v in values {
+            stats.push(v, values.len());
+        }
+        stats
+    }
+    fn push(&mut self, sample: f64, window_len: usize) {
+        let index = self.next_index;
+        self.next_index += 1;
+        self.samples.push_back(sample);
+        self.sum += sample;
+        self.sum_sq += sample * sample;
+        while matches!(self.min_deque.back(), Some(&(_, v)) if v >= sample) {
+            self.min_deque.pop_back();
+        }
+        self.min_deque.push_back((index, sample));
+        while matches!(self.max_deque.back(), Some(&(_, v)) if v <= sample) {
+            self.max_deque.pop_back();
+        }
+        self.max_deque.push_back((index, sample));
+        while self.samples.len() > window_len {
+            let removed = self.samples.pop_front().unwrap();
+            self.sum -= removed;
+            self.sum_sq -= removed * removed;
+        }
+        let oldest_valid = self.next_index.saturating_sub(window_len as u64);
+        while matches!(self.min_deque.front(), Some(&(idx, _)) if idx < oldest_valid) {
+            self.min_deque.pop_front();
+        }
+        while matches!(self.max_deque.front(), Some(&(idx, _)) if idx < oldest_valid) {
+            self.max_deque.pop_front();
+        }
+    }
+    fn mean(&self) -> f64 {
+        self.sum / self.samples.len().max(1) as f64
+    }
+    fn rms(&self) -> f64 {
+        (self.sum_sq / self.samples.len().max(1) as f64).sqrt()
+    }
+    /// RMS of the window's samples after applying the affine map `v -> gain * v +
+    /// offset`, computed directly from the running raw sum/sum-of-squares. RMS doesn't
+    /// commute with a translation the way mean/min/max do, so `gain * rms() + offset`
+    /// would be wrong whenever `offset != 0`.
+    fn affine_rms(&self, gain: f64, offset: f64) -> f64 {
+        let len = self.samples.len().max(1) as f64;
+        let mean_sq = self.sum_sq / len;
+        let mean = self.sum / len;
+        (gain * gain * mean_sq + 2. * gain * offset * mean + offset * offset).sqrt()
+    }
+    fn min(&self) -> f64 {
+        self.min_deque.front().map_or(0., |&(_, v)| v)
+    }
+    fn max(&self) -> f64 {
+        self.max_deque.front().map_or(0., |&(_, v)| v)
+    }
+}
+
 struct App {
     running: bool,
     task: ni_usb_6259::tasks::ContinuousAquisitionTask<2>,
-    readings: [Vec<f64>; 2],
+    readings: [VecDeque<f64>; 2],
+    pyramids: [Pyramid; 2],
+    total_dropped: [usize; 2],
+    stats: [WindowStats; 2],
+    retention_secs: f64,
+    show_profiler: bool,
     plot: ChannelPlot<2>,
 }
 impl App {
@@ -40,7 +164,12 @@ impl App {
         let channel_two = Channel::new("Voltage", 1.);
         Self {
             task,
-            readings: [vec![], vec![]],
+            readings: [VecDeque::new(), VecDeque::new()],
+            pyramids: [Pyramid::default(), Pyramid::default()],
+            total_dropped: [0, 0],
+            stats: [WindowStats::default(), WindowStats::default()],
+            retention_secs: 60.,
+            show_profiler: false,
             running: false,
             plot: ChannelPlot::new([channel_one, channel_two], sample_rate),
         }
@@ -48,13 +177,49 @@ impl App {
 }
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        puffin::GlobalProfiler::lock().new_frame();
+        if ctx.input(|i| i.key_pressed(Key::P)) {
+            self.show_profiler = !self.show_profiler;
+        }
+        if self.show_profiler {
+            puffin_egui::profiler_window(ctx);
+        }
         if self.running {
-            let all_readings = self.task.read_samples().unwrap();
-            for (vec, readings) in self.readings.iter_mut().zip(all_readings) {
-                vec.extend(readings);
+            let all_readings = {
+                puffin::profile_scope!("read_samples");
+                self.task.read_samples().unwrap()
+            };
+            // Retention must never shrink below the visible window, or the plot would
+            // scroll past the end of the buffer.
+            let retention = self.retention_secs.max(self.plot.plot_time);
+            let capacity = (retention * self.plot.sample_rate) as usize;
+            let stats_window = (self.plot.plot_time * self.plot.sample_rate) as usize;
+            let channels = itertools::izip!(
+                self.readings.iter_mut(),
+                self.pyramids.iter_mut(),
+                self.total_dropped.iter_mut(),
+                self.stats.iter_mut(),
+                all_readings
+            );
+            for (deque, pyramid, total_dropped, stats, readings) in channels {
+                for &sample in &readings {
+                    pyramid.push(sample);
+                    stats.push(sample, stats_window);
+                }
+                deque.extend(readings);
+                let overflow = deque.len().saturating_sub(capacity);
+                deque.drain(..overflow);
+                *total_dropped += overflow;
+                pyramid.trim(*total_dropped);
             }
             ctx.request_repaint();
         }
+        let data = self
+            .readings
+            .iter_mut()
+            .map(|d| &*d.make_contiguous())
+            .collect_vec();
+        let data: [&[f64]; 2] = data.try_into().unwrap();
         egui::panel::SidePanel::left("left_panel")
             .resizable(true)
             .show(ctx, |ui| {
@@ -68,6 +233,36 @@ impl eframe::App for App {
                         self.task.start().unwrap();
                     }
                 }
+                ui.add(
+                    egui::Slider::new(&mut self.retention_secs, self.plot.plot_time.min(600.)..=600.)
+                        .text("Retention (s)"),
+                );
+                self.plot.show_stats(&self.stats, &data, ui);
+                ui.separator();
+                ui.heading("Trigger");
+                ui.checkbox(&mut self.plot.trigger.enabled, "Enabled");
+                if self.plot.trigger.enabled {
+                    egui::ComboBox::from_label("Source")
+                        .selected_text(self.plot.channels[self.plot.trigger.source].name.clone())
+                        .show_ui(ui, |ui| {
+                            for (idx, channel) in self.plot.channels.iter().enumerate() {
+                                ui.selectable_value(&mut self.plot.trigger.source, idx, &channel.name);
+                            }
+                        });
+                    ui.add(
+                        egui::DragValue::new(&mut self.plot.trigger.level)
+                            .speed(0.01)
+                            .prefix("Level: "),
+                    );
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut self.plot.trigger.slope, Slope::Rising, "Rising");
+                        ui.selectable_value(&mut self.plot.trigger.slope, Slope::Falling, "Falling");
+                    });
+                    ui.add(
+                        egui::Slider::new(&mut self.plot.trigger.pre_trigger_frac, 0. ..=1.)
+                            .text("Pre-trigger"),
+                    );
+                }
             });
         egui::CentralPanel::default().show(ctx, |ui| {
             if ui.input(|i| i.key_pressed(Key::Num0)) {
@@ -76,18 +271,54 @@ impl eframe::App for App {
             if ui.input(|i| i.key_pressed(Key::Num1)) {
                 self.plot.active = 1;
             }
-            let data = self.readings.iter().map(|v| v.as_slice()).collect_vec();
-            self.plot.show(data.try_into().unwrap(), ui)
+            self.plot
+                .show(data, &self.pyramids, &self.total_dropped, ui)
         });
     }
 }
 
+#[derive(PartialEq, Clone, Copy)]
+enum Slope {
+    Rising,
+    Falling,
+}
+
+/// Oscilloscope-style edge trigger: freezes a periodic signal in place by re-anchoring
+/// the plot's x-axis to the most recent level crossing instead of the newest sample.
+struct Trigger {
+    enabled: bool,
+    source: usize,
+    level: f64,
+    slope: Slope,
+    pre_trigger_frac: f64,
+}
+impl Trigger {
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            source: 0,
+            level: 0.,
+            slope: Slope::Rising,
+            pre_trigger_frac: 0.5,
+        }
+    }
+    /// Scans `values` backward for the most recent adjacent pair crossing `level` in
+    /// the configured direction, returning the index of the later sample.
+    fn find_crossing(&self, values: &[f64]) -> Option<usize> {
+        (1..values.len()).rev().find(|&idx| match self.slope {
+            Slope::Rising => values[idx - 1] < self.level && values[idx] >= self.level,
+            Slope::Falling => values[idx - 1] > self.level && values[idx] <= self.level,
+        })
+    }
+}
+
 struct ChannelPlot<const N: usize> {
     channels: [Channel; N],
     active: usize,
     plot_time: f64,
     sample_rate: f64,
     points_per_channel: usize,
+    trigger: Trigger,
 }
 impl<const N: usize> ChannelPlot<N> {
     fn new(channels: [Channel; N], sample_rate: f64) -> Self {
@@ -97,9 +328,37 @@ impl<const N: usize> ChannelPlot<N> {
             plot_time: 10.,
             sample_rate,
             points_per_channel: 1000,
+            trigger: Trigger::new(),
         }
     }
-    fn show(&mut self, data: [&[f64]; N], ui: &mut Ui) {
+    /// Finds the trigger window in `data[i]`-relative indices: `(window_start,
+    /// window_end, anchor)`, where `anchor` is the crossing itself. Returns `None` to
+    /// free-run, either because triggering is off or no crossing was found within the
+    /// visible window.
+    fn trigger_window(
+        &self,
+        data: &[&[f64]; N],
+        values_per_window: usize,
+    ) -> Option<(usize, usize, usize)> {
+        if !self.trigger.enabled {
+            return None;
+        }
+        let source = data[self.trigger.source];
+        let scan_start = source.len().saturating_sub(values_per_window);
+        let anchor = scan_start + self.trigger.find_crossing(&source[scan_start..])?;
+        let pre_samples = (self.trigger.pre_trigger_frac * values_per_window as f64) as usize;
+        let post_samples = values_per_window.saturating_sub(pre_samples);
+        let start = anchor.saturating_sub(pre_samples);
+        let end = (anchor + post_samples).min(data[0].len());
+        Some((start, end, anchor))
+    }
+    fn show(
+        &mut self,
+        data: [&[f64]; N],
+        pyramids: &[Pyramid; N],
+        total_dropped: &[usize; N],
+        ui: &mut Ui,
+    ) {
         let legend = Legend::default()
             .position(egui::plot::Corner::RightTop)
             .text_style(egui::TextStyle::Heading);
@@ -132,24 +391,84 @@ impl<const N: usize> ChannelPlot<N> {
                 let active_channel = &self.channels[self.active];
                 let values_per_window = (self.plot_time * self.sample_rate) as usize;
                 let group_size = (values_per_window / self.points_per_channel).max(1);
-                let first_index =
-                    data[0].len().saturating_sub(values_per_window) / group_size * group_size;
+                // Pick the coarsest pyramid level whose bucket is no larger than
+                // `group_size`, so roughly `points_per_channel` buckets span the window.
+                let target_level = (group_size as f64).log2().floor() as usize;
+                // Anchor the window on the most recent trigger crossing, falling back to
+                // free-running (anchored on the newest sample) when none is found.
+                let (window_start, window_end, t_origin, x_offset) =
+                    match self.trigger_window(&data, values_per_window) {
+                        Some((start, end, anchor)) => {
+                            let x_offset = -self.plot_time * (1. - self.trigger.pre_trigger_frac);
+                            (start, end, anchor, x_offset)
+                        }
+                        None => {
+                            let end = data[0].len();
+                            let start = end.saturating_sub(values_per_window);
+                            (start, end, end, 0.)
+                        }
+                    };
                 for (i, channel) in self.channels.iter().enumerate() {
-                    let values = &data[i][first_index..];
-                    let mut points = Vec::with_capacity(self.points_per_channel);
-                    for i in 0..(values.len() / group_size) {
-                        let t = ((i * group_size) as f64 - values.len() as f64) / self.sample_rate;
-                        let y = values[(i * group_size)..((i + 1) * group_size)]
-                            .iter()
-                            .sum::<f64>()
-                            / group_size as f64;
-                        points.push([
-                            t,
-                            (y - channel.pos) / channel.zoom * active_channel.zoom
-                                + active_channel.pos,
-                        ])
+                    let level = target_level.min(pyramids[i].levels.len());
+                    let mut points = Vec::with_capacity(self.points_per_channel * 2);
+                    {
+                        puffin::profile_scope!("downsample");
+                        if level == 0 {
+                            let values = data[i];
+                            for (idx, &v) in
+                                values[window_start..window_end].iter().enumerate()
+                            {
+                                let idx = window_start + idx;
+                                let t = (idx as f64 - t_origin as f64) / self.sample_rate
+                                    + x_offset;
+                                let y = (v - channel.pos) / channel.zoom * active_channel.zoom
+                                    + active_channel.pos;
+                                points.push([t, y]);
+                                points.push([t, y]);
+                            }
+                        } else {
+                            let bucket_size = 1usize << level;
+                            let buckets = &pyramids[i].levels[level - 1];
+                            // `buckets` has had whole buckets trimmed off its front as
+                            // `total_dropped[i]` grew, so `buckets[0]` no longer starts at
+                            // raw index 0: it starts `dropped[level - 1]` buckets in. Go
+                            // through the bucket's absolute raw-sample position and back
+                            // out to `data[i]`-relative (subtracting `total_dropped[i]`)
+                            // to land in the same coordinate space as `window_start`/
+                            // `window_end`/`t_origin`.
+                            let bucket_offset = pyramids[i].dropped[level - 1];
+                            let local_origin = total_dropped[i] as i64;
+                            let abs_start = window_start as i64 + local_origin;
+                            let abs_end = window_end as i64 + local_origin;
+                            let start_bucket = (abs_start / bucket_size as i64
+                                - bucket_offset as i64)
+                                .max(0) as usize;
+                            let end_bucket = ((abs_end + bucket_size as i64 - 1)
+                                / bucket_size as i64
+                                - bucket_offset as i64)
+                                .max(0) as usize;
+                            let start_bucket = start_bucket.min(buckets.len());
+                            let end_bucket = end_bucket.min(buckets.len());
+                            for j in start_bucket..end_bucket {
+                                let (min, max) = buckets[j];
+                                let abs_idx = (bucket_offset + j) * bucket_size;
+                                let idx = abs_idx as f64 - local_origin as f64;
+                                let t = (idx - t_origin as f64) / self.sample_rate + x_offset;
+                                let y_min = (min - channel.pos) / channel.zoom
+                                    * active_channel.zoom
+                                    + active_channel.pos;
+                                let y_max = (max - channel.pos) / channel.zoom
+                                    * active_channel.zoom
+                                    + active_channel.pos;
+                                points.push([t, y_min]);
+                                points.push([t, y_max]);
+                            }
+                        }
                     }
-                    let mut line = Line::new(points).name(&channel.name);
+                    let mut line = {
+                        puffin::profile_scope!("build_line");
+                        Line::new(points).name(&channel.name)
+                    };
                     if self.active == i {
                         line = line.highlight(true);
                     }
@@ -157,6 +476,38 @@ impl<const N: usize> ChannelPlot<N> {
                 }
             });
     }
+    /// Renders mean/min/max/peak-to-peak/RMS for each channel over the current
+    /// `plot_time` window, scaled the same way each channel's line is drawn so the
+    /// numbers match what's on screen.
+    fn show_stats(&self, stats: &[WindowStats; N], data: &[&[f64]; N], ui: &mut Ui) {
+        let active = &self.channels[self.active];
+        let values_per_window = (self.plot_time * self.sample_rate) as usize;
+        // A trigger anchors the plot on an older crossing, not the newest sample, so
+        // the incrementally maintained (always-newest-window) `stats` would disagree
+        // with what's drawn; recompute directly over the same span in that case.
+        let trigger_window = self.trigger_window(data, values_per_window);
+        ui.separator();
+        ui.heading("Statistics");
+        for (i, channel) in self.channels.iter().enumerate() {
+            let scale = |v: f64| (v - channel.pos) / channel.zoom * active.zoom + active.pos;
+            let gain = active.zoom / channel.zoom;
+            let offset = active.pos - channel.pos * gain;
+            let windowed;
+            let stat = match trigger_window {
+                Some((start, end, _)) => {
+                    windowed = WindowStats::from_slice(&data[i][start..end]);
+                    &windowed
+                }
+                None => &stats[i],
+            };
+            ui.label(&channel.name);
+            ui.label(format!("mean: {:.4}", scale(stat.mean())));
+            ui.label(format!("min: {:.4}", scale(stat.min())));
+            ui.label(format!("max: {:.4}", scale(stat.max())));
+            ui.label(format!("p2p: {:.4}", scale(stat.max()) - scale(stat.min())));
+            ui.label(format!("rms: {:.4}", stat.affine_rms(gain, offset)));
+        }
+    }
 }
 
 fn metric_formatter(v: f64, range: &RangeInclusive<f64>) -> String {